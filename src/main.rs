@@ -1,13 +1,26 @@
+use std::net::SocketAddr;
 use std::time::Duration;
+use argh::FromArgs;
 use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::ecs::schedule::{ShouldRun, State as EngineState};
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::collide;
-use bevy::time::FixedTimestep;
 use bevy::window::PresentMode;
-use rand::prelude::SliceRandom;
+use bevy::winit::{UpdateMode, WinitSettings};
+use bevy_ggrs::{ggrs, GGRSPlugin, PlayerInputs, Rollback, RollbackIdProvider};
+use bevy_ggrs::ggrs::{PlayerType, SessionBuilder, UdpNonBlockingSocket};
+use bevy_fundsp::prelude::*;
+use bevy_hanabi::{Gradient, HanabiPlugin, ParticleEffect, ParticleEffectBundle, EffectAsset, Spawner, SizeOverLifetimeModifier, ColorOverLifetimeModifier, AccelModifier};
+use bevy_rapier2d::prelude::*;
+use bytemuck::{Pod, Zeroable};
 
 // Defines the amount of time that should elapse between each physics step.
-const TIME_STEP: f64 = 1.0 / 60.0;
+const FPS: usize = 60;
+const INPUT_DELAY: usize = 2;
+
+const INPUT_LEFT: u8 = 1 << 0;
+const INPUT_RIGHT: u8 = 1 << 1;
+const INPUT_PAUSE: u8 = 1 << 2;
 
 const SHURIKEN_UP_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 0.5);
 const SHURIKEN_DOWN_COLOR: Color = Color::rgba(0.2, 0.2, 0.2, 1.0);
@@ -25,8 +38,15 @@ const WALL_SIZE: Vec3 = Vec3::new(RIGHT_WALL * 2.0, 200.0, 0.0);
 
 const SHURIKEN_SIZE: Vec3 = Vec3::new(30.0, 30.0, 0.5);
 
-const GRAVITY: f32 = 0.25;
-const SHURIKEN_INIT_VELOCITY: f32 = 20.0;
+// Rapier works in real (per-second) units rather than the old per-frame deltas, so these are
+// the old 0.25 px/frame^2 and 20 px/frame figures scaled by the 60Hz step they were tuned at.
+const GRAVITY: f32 = 900.0;
+const SHURIKEN_INIT_VELOCITY: f32 = 1200.0;
+const SHURIKEN_SPIN: f32 = 6.0;
+
+const SHURIKEN_GROUP: Group = Group::GROUP_1;
+const PADDLE_GROUP: Group = Group::GROUP_2;
+const NINJA_GROUP: Group = Group::GROUP_3;
 
 const PADDLE_COLOR: Color = Color::rgb(0.0, 0.0, 1.0);
 const PADDLE_Y: f32 = -40.0;
@@ -37,13 +57,55 @@ const HEALTH_OFFSET: f32 = 20.0;
 const HEALTH_SIZE: Vec3 = Vec3::new(20.0, 10.0, 1.0);
 const HEALTH_COLOR: Color = Color::rgb(0.0, 1.0, 0.0);
 
+/// The single stage inside the schedule GGRS resimulates every rollback frame. bevy_ggrs 0.11
+/// takes that schedule as a plain `Schedule` via `GGRSPlugin::with_rollback_schedule` rather than
+/// exposing a stage label of its own, so this is the one we build and hand it.
+#[derive(StageLabel)]
+struct GgrsGameplayStage;
+
+/// GGRS session config: one bitflagged byte of input per player, addressed over UDP.
+struct GgrsConfig;
+impl ggrs::Config for GgrsConfig {
+    type Input = PaddleInput;
+    type State = u8;
+    type Address = SocketAddr;
+}
+
+/// Per-frame input for a single paddle, rollback-safe so GGRS can serialize it over the wire.
+#[derive(Copy, Clone, Pod, Zeroable, PartialEq, Eq, Debug, Default)]
+#[repr(C)]
+struct PaddleInput {
+    buttons: u8,
+}
+
+/// A small xorshift PRNG whose seed is part of the rollback-registered state, so every peer
+/// re-simulating the same frame draws the same ninja in `spawn_shurikens`.
+#[derive(Resource, Copy, Clone, Reflect, FromReflect, Default)]
+struct RngState(u64);
+
+impl RngState {
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Picks an index in `0..len`, mirroring `SliceRandom::choose` without diverging RNG state
+    /// between peers.
+    fn gen_range(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
 /// Probable difficulty equation: `y=1.1^(0.7x)/20`
-#[derive(Resource)]
+#[derive(Resource, Clone, Reflect, FromReflect)]
 struct State {
     lives: usize,
     blocked: usize,
     timer: Timer,
-    key_debounce: bool,
 }
 
 impl Default for State {
@@ -52,7 +114,6 @@ impl Default for State {
             lives: MAX_HEALTH,
             blocked: 0,
             timer: Timer::new(Duration::from_millis((1.1_f32.powf(0.0) * 1000.0 * 1.5) as u64), TimerMode::Repeating),
-            key_debounce: false,
         }
     }
 }
@@ -65,28 +126,156 @@ struct HealthMarker(usize);
 #[derive(Component)]
 struct Shuriken;
 
-/// A 1-D velocity
-#[derive(Component)]
-struct Velocity(f32);
-
 /// A ninja that can be hit
 /// Also where the shurikens can be spawned
 #[derive(Component)]
 struct Ninja;
 
-/// The movable paddle to block shurikens, along with its position
+/// The movable paddle to block shurikens, along with its position and whether it just moved
+/// (so a held key doesn't repeat the move every rollback-simulated frame).
+#[derive(Component, Copy, Clone, Reflect, FromReflect, Default)]
+struct Paddle {
+    pos: u32,
+    debounce: bool,
+}
+
+/// Which GGRS player handle drives this paddle.
+#[derive(Component)]
+struct Player(usize);
+
+/// Debounces the shared pause input the same way `Paddle::debounce` does for movement, so
+/// holding Escape down doesn't toggle `AppState::Paused` on and off every resimulated frame.
+/// Rollback-registered since `toggle_pause` now reads `PlayerInputs` instead of local keyboard
+/// state, so both peers need to agree on whether the button's already been actioned.
+#[derive(Resource, Clone, Default, Reflect, FromReflect)]
+struct PauseDebounce(bool);
+
+/// Marks a shuriken that has already scored a paddle block, so it keeps bouncing around the
+/// playfield afterward without being counted a second time. Rollback-registered since
+/// `score_collisions` inserts it from inside the GGRS rollback schedule.
+#[derive(Component, Clone, Reflect, FromReflect, Default)]
+struct Scored;
+
+/// Drives which screen is shown; `Paused` sits on top of `Playing` on the state stack so
+/// resuming doesn't need to remember where we came from.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+enum AppState {
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Tags UI entities belonging to the main menu, so `teardown_menu` can sweep them all.
+#[derive(Component)]
+struct MenuUi;
+
+/// Tags UI entities belonging to the game-over screen.
+#[derive(Component)]
+struct GameOverUi;
+
+/// Reusable one-shot particle effects spawned at a collision point; built once in `setup` so
+/// `score_collisions` just clones a handle instead of rebuilding a `EffectAsset` every hit.
+#[derive(Resource)]
+struct VfxAssets {
+    block_effect: Handle<EffectAsset>,
+    hit_effect: Handle<EffectAsset>,
+}
+
+/// Gameplay events that the audio subsystem reacts to. Kept separate from the systems that
+/// raise them so e.g. `score_collisions` doesn't need to know anything about synths.
+#[derive(Clone)]
+enum SfxEvent {
+    Block,
+    Hit,
+    Countdown,
+}
+
+/// Key types `bevy_fundsp` uses to look up each registered DSP graph's `Handle<DspSource>`.
+struct BlockTone;
+struct HitThud;
+struct CountdownBeep;
+
+/// Gates `spawn_shurikens` until the pre-round announcer has played its three beats. Part of
+/// the rollback-registered state since it decides whether the match has started yet.
+#[derive(Resource, Clone, Reflect, FromReflect)]
+struct StartTimer {
+    timer: Timer,
+    beats_remaining: u8,
+}
+
+impl StartTimer {
+    fn new() -> Self {
+        Self {
+            timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+            beats_remaining: 3,
+        }
+    }
+
+    fn ready(&self) -> bool {
+        self.beats_remaining == 0
+    }
+}
+
+impl Default for StartTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CLI switches, modeled on bevymark's benchmark flag.
+#[derive(FromArgs)]
+struct Args {
+    /// run in stress/benchmark mode: uncapped framerate, a diagnostics HUD, and N shurikens
+    /// spawned per tick at every spawner instead of one on the difficulty timer
+    #[argh(switch, short = 'b')]
+    bench: bool,
+
+    /// shurikens spawned per tick per spawner in bench mode
+    #[argh(option, default = "50")]
+    spawn_rate: u32,
+
+    /// UDP port this peer listens on (default 7000)
+    #[argh(option)]
+    local_port: Option<u16>,
+
+    /// address of the remote peer, as ip:port
+    #[argh(option)]
+    remote_addr: Option<SocketAddr>,
+
+    /// GGRS player handle for this peer, 0 or 1
+    #[argh(option)]
+    player_handle: Option<usize>,
+}
+
+/// Present only in bench mode; `spawn_rate` drives `bench_spawn_shurikens`.
+#[derive(Resource)]
+struct BenchMode {
+    spawn_rate: u32,
+}
+
+/// Tracks the highest entity count seen so far, reported alongside the blocked-count message
+/// when the game exits.
+#[derive(Resource, Default)]
+struct PeakEntities(usize);
+
+/// Tags the bench-mode FPS/frame-time/entity-count overlay so `update_diagnostics_hud` can
+/// find it.
 #[derive(Component)]
-struct Paddle(u32);
+struct DiagnosticsHud;
 
 fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::hex("25e6ee").unwrap()))
+    let args: Args = argh::from_env();
+
+    let mut app = App::new();
+
+    app.insert_resource(ClearColor(Color::hex("25e6ee").unwrap()))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             window: WindowDescriptor {
                 width: RIGHT_WALL * 2.0,
                 height: TOP_WALL * 2.0,
                 title: "Shuriken Workshop".to_string(),
-                present_mode: PresentMode::AutoVsync,
+                present_mode: if args.bench { PresentMode::Immediate } else { PresentMode::AutoVsync },
                 resizable: false,
                 ..default()
             },
@@ -94,37 +283,206 @@ fn main() {
             exit_on_all_closed: true,
             close_when_requested: true,
         }))
+        .add_plugin(HanabiPlugin)
+        .add_plugin(DspPlugin::default())
+        .add_dsp_source(block_tone, SourceType::Dynamic)
+        .add_dsp_source(hit_thud, SourceType::Dynamic)
+        .add_dsp_source(countdown_beep, SourceType::Dynamic)
+        .add_event::<SfxEvent>()
         .init_resource::<State>()
+        .init_resource::<StartTimer>()
+        .init_resource::<PauseDebounce>()
+        .insert_resource(RngState(seed_from_match_start()))
+        .add_state(AppState::Menu)
         .add_startup_system(setup)
-        .add_system_set(SystemSet::new()
-            .with_run_criteria(FixedTimestep::step(TIME_STEP))
-            .with_system(check_collisions)
-            .with_system(do_physics.before(check_collisions))
-            .with_system(spawn_shurikens.after(check_collisions))
-            .with_system(update_paddle.after(check_collisions))
-        )
+        .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu))
+        .add_system_set(SystemSet::on_update(AppState::Menu).with_system(menu_input))
+        .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(teardown_menu))
+        .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(start_round))
+        .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(enter_game_over))
+        .add_system_set(SystemSet::on_update(AppState::GameOver).with_system(game_over_input))
+        .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(teardown_game_over))
         .add_system(update_scoreboard)
-        .run();
+        .add_system(report_on_exit)
+        .add_system(announce_sfx.before(play_sfx))
+        .add_system(play_sfx);
+
+    if args.bench {
+        app.insert_resource(WinitSettings {
+            focused_mode: UpdateMode::Continuous,
+            unfocused_mode: UpdateMode::Continuous,
+            ..default()
+        })
+            .insert_resource(BenchMode { spawn_rate: args.spawn_rate })
+            .init_resource::<PeakEntities>()
+            .add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_startup_system(setup_diagnostics_hud)
+            .add_system(update_diagnostics_hud)
+            .add_system(track_peak_entities);
+    } else {
+        app.init_resource::<PeakEntities>();
+    }
+
+    // Note: rapier's internal rigid-body/contact state isn't part of the rollback snapshot
+    // below, so it's along for the ride rather than restored on resimulation. Acceptable for
+    // the physical bounce, which is cosmetic, but nothing that mutates rollback-registered
+    // `State` is allowed to depend on it — see `score_collisions`, which recomputes contacts
+    // itself from `Transform` inside the rollback schedule instead of reading rapier's own
+    // events.
+    app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.0))
+        .insert_resource(RapierConfiguration {
+            gravity: Vec2::new(0.0, -GRAVITY),
+            ..default()
+        });
+
+    // The gameplay set only ticks while `AppState::Playing`; `Paused` freezes it in place
+    // without despawning anything, and `GameOver`/`Menu` simply don't advance it. Rapier steps
+    // on its own schedule, so `toggle_pause` additionally stops its pipeline while paused.
+    // `pause_set` is kept separate from `gameplay_set` since it needs to keep running while
+    // `Paused`, not just `Playing`.
+    //
+    // In a real match both sets have to live inside the schedule GGRS resimulates on rollback,
+    // so every peer recomputes the same outcome. Bench mode never starts a session (see below),
+    // and that schedule only steps once a `Session` resource exists, so there both sets run on
+    // the ordinary schedule instead, gated only by `AppState::Playing`/`Paused` directly --
+    // otherwise score_collisions/cleanup_stray_shurikens/update_paddle would never run at all in
+    // bench mode, and shurikens would spawn forever without ever being scored or cleaned up.
+    let gameplay_set = SystemSet::new()
+        .with_run_criteria(run_if_playing)
+        .with_system(tick_start_timer)
+        .with_system(update_shuriken_color)
+        .with_system(update_paddle)
+        .with_system(score_collisions)
+        .with_system(cleanup_stray_shurikens);
+    let pause_set = SystemSet::new().with_run_criteria(run_if_playing_or_paused).with_system(toggle_pause);
+
+    let mut gameplay_schedule = Schedule::default();
+    gameplay_schedule.add_stage(GgrsGameplayStage, SystemStage::parallel());
+
+    if args.bench {
+        app.add_system_set(gameplay_set).add_system_set(pause_set);
+    } else {
+        gameplay_schedule.add_system_set_to_stage(
+            GgrsGameplayStage,
+            gameplay_set.with_system(spawn_shurikens.after(tick_start_timer)),
+        );
+        gameplay_schedule.add_system_set_to_stage(GgrsGameplayStage, pause_set);
+    }
+
+    GGRSPlugin::<GgrsConfig>::new()
+        .with_update_frequency(FPS)
+        .with_input_system(read_local_input)
+        .register_rollback_component::<Transform>()
+        .register_rollback_component::<Paddle>()
+        .register_rollback_component::<Scored>()
+        .register_rollback_resource::<PauseDebounce>()
+        .register_rollback_resource::<State>()
+        .register_rollback_resource::<RngState>()
+        .register_rollback_resource::<StartTimer>()
+        .with_rollback_schedule(gameplay_schedule)
+        .build(&mut app);
+
+    if args.bench {
+        // Stress-testing shouldn't need a peer: no socket, no `--remote-addr`, and no frame cap
+        // from GGRS's fixed update frequency. `bench_spawn_shurikens` runs flat-out on the
+        // ordinary schedule instead, gated only by `AppState::Playing`.
+        app.add_system(bench_spawn_shurikens.with_run_criteria(run_if_playing));
+    } else {
+        let local_handle = local_handle();
+        let socket = UdpNonBlockingSocket::bind_to_port(local_port()).expect("failed to bind UDP socket");
+        let session = SessionBuilder::<GgrsConfig>::new()
+            .with_num_players(2)
+            .with_input_delay(INPUT_DELAY)
+            .add_player(PlayerType::Local, local_handle)
+            .expect("failed to add local player")
+            .add_player(PlayerType::Remote(remote_addr()), 1 - local_handle)
+            .expect("failed to add remote player")
+            .start_p2p_session(socket)
+            .expect("failed to start GGRS session");
+
+        app.insert_resource(bevy_ggrs::Session::P2PSession(session));
+    }
+
+    app.run();
+}
+
+/// Every peer derives the same seed at startup so the shuriken-choosing xorshift stays in lock
+/// step even before the first input is exchanged.
+fn seed_from_match_start() -> u64 {
+    0x9E3779B97F4A7C15
+}
+
+/// Matchmaking is out of scope for now: peers are told about each other via
+/// `--local-port <port> --remote-addr <ip:port> --player-handle <0|1>`. Both peers need to agree
+/// on who's 0 and who's 1 ahead of time, since GGRS identifies players by handle, not address.
+fn cli_arg(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|a| a != flag).nth(1)
+}
+
+fn local_port() -> u16 {
+    cli_arg("--local-port").and_then(|p| p.parse().ok()).unwrap_or(7000)
+}
+
+fn remote_addr() -> SocketAddr {
+    cli_arg("--remote-addr").and_then(|a| a.parse().ok()).expect("--remote-addr <ip:port> is required")
+}
+
+/// Which GGRS player handle this peer plays as; the other peer must be started with the
+/// opposite value. Previously hardcoded to 0 on both ends, which meant two peers launched
+/// against each other both claimed handle 0 and desynced immediately.
+fn local_handle() -> usize {
+    match cli_arg("--player-handle").and_then(|h| h.parse::<usize>().ok()) {
+        Some(0) => 0,
+        Some(1) => 1,
+        _ => panic!("--player-handle <0|1> is required"),
+    }
+}
+
+/// Reads this peer's local keyboard state into the bitflagged `PaddleInput` GGRS will ship to
+/// the remote peer (and replay during rollback).
+fn read_local_input(_handle: In<ggrs::PlayerHandle>, keyboard_input: Res<Input<KeyCode>>) -> PaddleInput {
+    let mut buttons = 0u8;
+    if keyboard_input.pressed(KeyCode::Left) {
+        buttons |= INPUT_LEFT;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        buttons |= INPUT_RIGHT;
+    }
+    if keyboard_input.pressed(KeyCode::Escape) {
+        buttons |= INPUT_PAUSE;
+    }
+    PaddleInput { buttons }
 }
 
 fn setup(
-    mut commands: Commands
+    mut commands: Commands,
+    mut rip: ResMut<RollbackIdProvider>,
+    mut effects: ResMut<Assets<EffectAsset>>,
 ) {
     commands.spawn(Camera2dBundle::default());
 
+    commands.insert_resource(VfxAssets {
+        block_effect: effects.add(burst_effect("block", Color::rgba(0.3, 0.5, 1.0, 1.0))),
+        hit_effect: effects.add(burst_effect("hit", Color::rgba(1.0, 0.2, 0.2, 1.0))),
+    });
+
     // Spawn the wall behind the ninjas
-    commands.spawn(SpriteBundle {
-        sprite: Sprite {
-            color: Color::rgb(0.0, 0.0, 0.0),
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, BOTTOM_WALL + WALL_SIZE.y / 2.0, 0.0),
-            scale: WALL_SIZE,
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgb(0.0, 0.0, 0.0),
+                ..default()
+            },
+            transform: Transform {
+                translation: Vec3::new(0.0, BOTTOM_WALL + WALL_SIZE.y / 2.0, 0.0),
+                scale: WALL_SIZE,
+                ..default()
+            },
             ..default()
         },
-        ..default()
-    });
+        RigidBody::Fixed,
+        Collider::cuboid(0.5, 0.5),
+    ));
 
     // Spawn ninjas and shuriken spawners
     for x_pos in ((LEFT_WALL + (RIGHT_WALL / NUM_NINJAS as f32)) as i32..RIGHT_WALL as i32).step_by((RIGHT_WALL * 2.0 / NUM_NINJAS as f32) as usize) {
@@ -141,26 +499,72 @@ fn setup(
                 },
                 ..default()
             },
-            Ninja
+            Ninja,
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 0.5),
+            CollisionGroups::new(NINJA_GROUP, SHURIKEN_GROUP),
         ));
     }
 
-    commands.spawn((
-        SpriteBundle {
-            sprite: Sprite {
-                color: PADDLE_COLOR,
-                ..default()
-            },
-            transform: Transform {
-                translation: Vec3::new(LEFT_WALL + RIGHT_WALL / (NUM_NINJAS as f32), PADDLE_Y, 0.0),
-                scale: PADDLE_SIZE,
+    // Each connected player gets their own paddle, offset so they don't overlap at rest.
+    for handle in 0..2usize {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: PADDLE_COLOR,
+                    ..default()
+                },
+                transform: Transform {
+                    translation: Vec3::new(LEFT_WALL + RIGHT_WALL / (NUM_NINJAS as f32), PADDLE_Y - handle as f32 * 30.0, 0.0),
+                    scale: PADDLE_SIZE,
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        Paddle(0)
-    ));
+            Paddle { pos: 0, debounce: false },
+            Player(handle),
+            Rollback::new(rip.next_id()),
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 0.5),
+            CollisionGroups::new(PADDLE_GROUP, SHURIKEN_GROUP),
+        ));
+    }
+
+    spawn_health_markers(&mut commands);
+}
+
+/// Builds a short radial burst: particles launch outward, fall under gravity, and fade from
+/// `color` to transparent over their lifetime. Shared shape for both the block and hit sparks,
+/// just tinted differently.
+fn burst_effect(name: &str, color: Color) -> EffectAsset {
+    let mut gradient = Gradient::new();
+    gradient.add_key(0.0, color.as_rgba_f32().into());
+    gradient.add_key(1.0, Vec4::new(color.r(), color.g(), color.b(), 0.0));
 
+    EffectAsset {
+        name: name.to_string(),
+        capacity: 256,
+        spawner: Spawner::once(30.0.into(), true),
+        ..default()
+    }
+        .init(bevy_hanabi::SetPositionSphereModifier {
+            center: Vec3::ZERO,
+            radius: 2.0.into(),
+            dimension: bevy_hanabi::ShapeDimension::Volume,
+        })
+        .init(bevy_hanabi::SetVelocitySphereModifier {
+            center: Vec3::ZERO,
+            speed: 180.0.into(),
+        })
+        .init(bevy_hanabi::SetAttributeModifier::new(bevy_hanabi::Attribute::LIFETIME, 0.4.into()))
+        .update(AccelModifier::constant(Vec3::new(0.0, -GRAVITY, 0.0)))
+        .render(ColorOverLifetimeModifier { gradient })
+        .render(SizeOverLifetimeModifier { gradient: Gradient::constant(Vec2::splat(4.0)) })
+}
+
+/// Spawns one marker per starting life. Pulled out of `setup` so restarting from the
+/// game-over screen can recreate them without re-running the whole level setup.
+fn spawn_health_markers(commands: &mut Commands) {
     for (i, x_pos) in ((LEFT_WALL + (RIGHT_WALL / MAX_HEALTH as f32)) as i32..RIGHT_WALL as i32).step_by((RIGHT_WALL * 2.0 / MAX_HEALTH as f32) as usize).enumerate() {
         commands.spawn((
             SpriteBundle {
@@ -181,36 +585,36 @@ fn setup(
 }
 
 fn update_paddle(
-    mut state: ResMut<State>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mut paddle: Query<(&mut Paddle, &mut Transform)>
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut paddle: Query<(&mut Paddle, &Player, &mut Transform)>
 ) {
-    if state.key_debounce {
-        if !(keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::Right)) {
-            state.key_debounce = false;
+    for (mut paddle, player, mut transform) in &mut paddle {
+        let (input, _) = inputs[player.0];
+
+        if paddle.debounce {
+            if input.buttons & (INPUT_LEFT | INPUT_RIGHT) == 0 {
+                paddle.debounce = false;
+            }
+            continue;
         }
-        return;
-    }
 
-    let (mut paddle, mut transform) = paddle.single_mut();
-    if keyboard_input.pressed(KeyCode::Left) && paddle.0 > 0 {
-        paddle.0 -= 1;
-        state.key_debounce = true;
-    } else if keyboard_input.pressed(KeyCode::Right) && paddle.0 < NUM_NINJAS - 1 {
-        paddle.0 += 1;
-        state.key_debounce = true;
-    }
+        if input.buttons & INPUT_LEFT != 0 && paddle.pos > 0 {
+            paddle.pos -= 1;
+            paddle.debounce = true;
+        } else if input.buttons & INPUT_RIGHT != 0 && paddle.pos < NUM_NINJAS - 1 {
+            paddle.pos += 1;
+            paddle.debounce = true;
+        }
 
-    transform.translation.x = LEFT_WALL + RIGHT_WALL / (NUM_NINJAS as f32) + RIGHT_WALL * 2.0 * paddle.0 as f32 / NUM_NINJAS as f32;
+        transform.translation.x = LEFT_WALL + RIGHT_WALL / (NUM_NINJAS as f32) + RIGHT_WALL * 2.0 * paddle.pos as f32 / NUM_NINJAS as f32;
+    }
 }
 
-fn do_physics(
-    mut shuriken_query: Query<(&mut Sprite, &mut Transform, &mut Velocity), With<Shuriken>>
-) {
-    for (mut sprite, mut transform, mut velocity) in &mut shuriken_query {
-        transform.translation.y += velocity.0;
-        velocity.0 -= GRAVITY;
-        if velocity.0 < 0.0 {
+/// Rapier owns the actual position/velocity integration now; this just keeps the sprite-darkening
+/// cue from the old manual physics once a shuriken is past its arc and falling.
+fn update_shuriken_color(mut shuriken_query: Query<(&mut Sprite, &Velocity), With<Shuriken>>) {
+    for (mut sprite, velocity) in &mut shuriken_query {
+        if velocity.linvel.y < 0.0 {
             sprite.color = SHURIKEN_DOWN_COLOR;
         }
     }
@@ -219,56 +623,135 @@ fn do_physics(
 fn spawn_shurikens(
     mut commands: Commands,
     mut state: ResMut<State>,
+    mut rng: ResMut<RngState>,
+    mut rip: ResMut<RollbackIdProvider>,
+    start_timer: Res<StartTimer>,
     spawner_query: Query<&Transform, With<Ninja>>
 ) {
-    state.timer.tick(Duration::from_millis((TIME_STEP * 1000.0) as u64));
+    if !start_timer.ready() {
+        return;
+    }
+
+    state.timer.tick(Duration::from_millis((1000.0 / FPS as f32) as u64));
     if state.timer.finished() {
         let spawners = spawner_query.iter().collect::<Vec<_>>();
-        let chosen = **spawners.choose(&mut rand::thread_rng()).unwrap();
-        commands.spawn((
-            SpriteBundle {
-                sprite: Sprite {
-                    color: SHURIKEN_UP_COLOR,
-                    ..default()
-                },
-                transform: chosen.with_scale(SHURIKEN_SIZE),
-                ..default()
-            },
-            Shuriken,
-            Velocity(SHURIKEN_INIT_VELOCITY),
-        ));
+        let chosen = *spawners[rng.gen_range(spawners.len())];
+        spawn_one_shuriken(&mut commands, &mut rip, chosen);
         state.timer.reset();
     }
 }
 
-fn check_collisions(
+/// Bench-mode replacement for `spawn_shurikens`: ignores the difficulty timer entirely and
+/// floods every spawner with `BenchMode::spawn_rate` shurikens per tick, so the physics and
+/// collision-handling code can be profiled under thousands of simultaneous shurikens.
+fn bench_spawn_shurikens(
+    mut commands: Commands,
+    mut rip: ResMut<RollbackIdProvider>,
+    bench_mode: Res<BenchMode>,
+    spawner_query: Query<&Transform, With<Ninja>>
+) {
+    for spawner in &spawner_query {
+        for _ in 0..bench_mode.spawn_rate {
+            spawn_one_shuriken(&mut commands, &mut rip, *spawner);
+        }
+    }
+}
+
+/// Spawns a single shuriken at `spawner`'s position, shared by the normal difficulty-timer
+/// spawn path and the bench-mode flood.
+fn spawn_one_shuriken(commands: &mut Commands, rip: &mut RollbackIdProvider, spawner: Transform) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: SHURIKEN_UP_COLOR,
+                ..default()
+            },
+            transform: spawner.with_scale(SHURIKEN_SIZE),
+            ..default()
+        },
+        Shuriken,
+        RigidBody::Dynamic,
+        Collider::cuboid(0.5, 0.5),
+        GravityScale(1.0),
+        Velocity { linvel: Vec2::new(0.0, SHURIKEN_INIT_VELOCITY), angvel: SHURIKEN_SPIN },
+        CollisionGroups::new(SHURIKEN_GROUP, PADDLE_GROUP | NINJA_GROUP),
+        Rollback::new(rip.next_id()),
+    ));
+}
+
+/// Two axis-aligned boxes, given as center + half-extent, overlap.
+fn aabb_overlap(a_pos: Vec2, a_half: Vec2, b_pos: Vec2, b_half: Vec2) -> bool {
+    (a_pos.x - b_pos.x).abs() <= a_half.x + b_half.x && (a_pos.y - b_pos.y).abs() <= a_half.y + b_half.y
+}
+
+/// Scores shuriken contacts with a direct AABB sweep over `Transform`, rather than rapier's own
+/// `CollisionEvent` stream. `CollisionEvent`s surface once per rendered frame off a physics
+/// pipeline that isn't part of the rollback snapshot, so mutating the rollback-registered
+/// `State` from them desynced peers that happened to resimulate a frame differently. Running
+/// the sweep itself inside the GGRS rollback schedule ties the mutation to the resimulated
+/// frame instead. A paddle hit scores once (via `Scored`) and rescales the difficulty timer
+/// without despawning, leaving the shuriken to keep bouncing; a ninja hit despawns it.
+fn score_collisions(
     mut commands: Commands,
     mut state: ResMut<State>,
-    shuriken_query: Query<(Entity, &Transform, &Velocity), With<Shuriken>>,
-    ninja_query: Query<&Transform, With<Ninja>>,
+    vfx: Res<VfxAssets>,
+    shuriken_query: Query<(Entity, &Transform, Option<&Scored>), With<Shuriken>>,
     paddle_query: Query<&Transform, With<Paddle>>,
+    ninja_query: Query<&Transform, With<Ninja>>,
 ) {
-    let paddle = paddle_query.single();
-    for (shuriken, shuriken_pos) in shuriken_query.iter().filter(|(_, _, v)| v.0 < 0.0).map(|(e, t, _)| (e, t)) {
-        if collide(shuriken_pos.translation, SHURIKEN_SIZE.truncate(), paddle.translation, PADDLE_SIZE.truncate()).is_some() {
+    for (shuriken, shuriken_transform, scored) in &shuriken_query {
+        let shuriken_pos = shuriken_transform.translation.truncate();
+        let shuriken_half = shuriken_transform.scale.truncate() * 0.5;
+
+        let hit_ninja = ninja_query.iter().any(|ninja_transform| {
+            aabb_overlap(shuriken_pos, shuriken_half, ninja_transform.translation.truncate(), ninja_transform.scale.truncate() * 0.5)
+        });
+        if hit_ninja {
+            spawn_burst(&mut commands, &vfx.hit_effect, shuriken_transform.translation);
             commands.entity(shuriken).despawn();
+            state.lives = state.lives.saturating_sub(1);
+            continue;
+        }
+
+        if scored.is_some() {
+            continue;
+        }
+
+        let hit_paddle = paddle_query.iter().any(|paddle_transform| {
+            aabb_overlap(shuriken_pos, shuriken_half, paddle_transform.translation.truncate(), paddle_transform.scale.truncate() * 0.5)
+        });
+        if hit_paddle {
+            spawn_burst(&mut commands, &vfx.block_effect, shuriken_transform.translation);
             state.blocked += 1;
             state.timer = Timer::new(Duration::from_millis((1.1_f32.powf(1.0 / state.blocked as f32) * 1000.0 * 1.5) as u64), TimerMode::Repeating);
-            continue;
+            commands.entity(shuriken).insert(Scored);
         }
+    }
+}
 
-        for ninja in &ninja_query {
-            if collide(shuriken_pos.translation, SHURIKEN_SIZE.truncate(), ninja.translation, NINJA_SIZE.truncate()).is_some() {
-                commands.entity(shuriken).despawn();
-                state.lives -= 1;
-            }
+/// Shurikens that miss every paddle and ninja just keep falling under rapier gravity forever;
+/// despawn anything that's drifted well past the bottom wall so bench mode (and long matches)
+/// don't accumulate unbounded entities.
+fn cleanup_stray_shurikens(mut commands: Commands, shuriken_query: Query<(Entity, &Transform), With<Shuriken>>) {
+    for (entity, transform) in &shuriken_query {
+        if transform.translation.y < BOTTOM_WALL - SHURIKEN_SIZE.y * 10.0 {
+            commands.entity(entity).despawn();
         }
     }
 }
 
+/// Spawns a one-shot particle burst at `position` using a previously-built `EffectAsset` handle.
+fn spawn_burst(commands: &mut Commands, effect: &Handle<EffectAsset>, position: Vec3) {
+    commands.spawn(ParticleEffectBundle {
+        effect: ParticleEffect::new(effect.clone()),
+        transform: Transform::from_translation(position),
+        ..default()
+    });
+}
+
 fn update_scoreboard(
     mut commands: Commands,
-    mut exit: EventWriter<AppExit>,
+    mut app_state: ResMut<EngineState<AppState>>,
     state: Res<State>,
     health_query: Query<(Entity, &HealthMarker)>
 ) {
@@ -278,8 +761,278 @@ fn update_scoreboard(
         }
     }
 
-    if state.lives == 0 {
-        println!("GAME OVER! You blocked {} shuriken(s)", state.blocked);
-        exit.send(AppExit);
+    if state.lives == 0 && app_state.current() == &AppState::Playing {
+        app_state.set(AppState::GameOver).ok();
+    }
+}
+
+/// Gameplay's run criteria in the GGRS schedule: only `Playing` advances the simulation, so
+/// `Paused` freezes it mid-frame and `Menu`/`GameOver` never start it.
+fn run_if_playing(app_state: Res<EngineState<AppState>>) -> ShouldRun {
+    if app_state.current() == &AppState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Resets the pre-round announcer every time we enter `Playing`, whether that's the first
+/// match start or a restart from the game-over screen.
+fn start_round(mut commands: Commands) {
+    commands.insert_resource(StartTimer::new());
+}
+
+/// Ticks the countdown on the same fixed-step clock as the rest of gameplay (not `Res<Time>`)
+/// so it stays in lockstep across GGRS peers. Only mutates `StartTimer`; `announce_sfx` is the
+/// one that turns a beat into a sound, since this runs inside the resimulated rollback schedule.
+fn tick_start_timer(mut start_timer: ResMut<StartTimer>) {
+    if start_timer.ready() {
+        return;
+    }
+
+    start_timer.timer.tick(Duration::from_millis((1000.0 / FPS as f32) as u64));
+    if start_timer.timer.just_finished() {
+        start_timer.beats_remaining -= 1;
+    }
+}
+
+/// Turns settled changes in rollback-registered state into `SfxEvent`s. `tick_start_timer` and
+/// `score_collisions` used to send these directly, but both run inside the GGRS rollback
+/// schedule, which re-executes on every resimulated frame -- a rollback-and-replay could fire
+/// the same beep or block/hit cue several times for what's really one confirmed frame. Running
+/// on the ordinary (non-resimulated) schedule and diffing against the last value this system
+/// saw means each cue plays exactly once per actual change, no matter how many times GGRS
+/// replayed the frame that produced it.
+fn announce_sfx(
+    start_timer: Res<StartTimer>,
+    state: Res<State>,
+    mut sfx: EventWriter<SfxEvent>,
+    mut last_beats_remaining: Local<u8>,
+    mut last_blocked: Local<usize>,
+    mut last_lives: Local<usize>,
+) {
+    if start_timer.beats_remaining < *last_beats_remaining {
+        for _ in 0..(*last_beats_remaining - start_timer.beats_remaining) {
+            sfx.send(SfxEvent::Countdown);
+        }
+    }
+    *last_beats_remaining = start_timer.beats_remaining;
+
+    if state.blocked > *last_blocked {
+        for _ in 0..(state.blocked - *last_blocked) {
+            sfx.send(SfxEvent::Block);
+        }
+    }
+    *last_blocked = state.blocked;
+
+    if state.lives < *last_lives {
+        for _ in 0..(*last_lives - state.lives) {
+            sfx.send(SfxEvent::Hit);
+        }
+    }
+    *last_lives = state.lives;
+}
+
+/// Plays the synth matching each gameplay event as it comes in.
+fn play_sfx(mut events: EventReader<SfxEvent>, dsp_assets: Res<DspAssets>, audio: Res<Audio>) {
+    for event in events.iter() {
+        let source = match event {
+            SfxEvent::Block => dsp_assets.source::<BlockTone>(),
+            SfxEvent::Hit => dsp_assets.source::<HitThud>(),
+            SfxEvent::Countdown => dsp_assets.source::<CountdownBeep>(),
+        };
+        audio.play(source);
+    }
+}
+
+/// A rising tone for a successful block: a quick sine sweep with an attack/decay envelope.
+fn block_tone() -> impl AudioUnit32 {
+    envelope(|t| (t * 100.0).min(1.0) * (1.0 - t * 6.0).max(0.0)) * (envelope(|t| 660.0 + t * 1320.0) >> sine())
+}
+
+/// A low thud for taking damage: a short decaying low sine, no attack.
+fn hit_thud() -> impl AudioUnit32 {
+    envelope(|t| (1.0 - t * 4.0).max(0.0)) * sine_hz(110.0)
+}
+
+/// A single countdown beep, reused for all three beats.
+fn countdown_beep() -> impl AudioUnit32 {
+    envelope(|t| (1.0 - t * 10.0).max(0.0)) * sine_hz(440.0)
+}
+
+/// Either player's Escape toggles `Paused` on top of `Playing` without disturbing the stack
+/// beneath it, so resuming just pops back to whatever was running. Reads the pause bit off
+/// `PlayerInputs` rather than local keyboard state and runs inside the GGRS rollback schedule
+/// (see `run_if_playing_or_paused`), so both peers toggle on the same simulated frame instead
+/// of each one pausing only its own local view of the match.
+fn toggle_pause(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut debounce: ResMut<PauseDebounce>,
+    mut app_state: ResMut<EngineState<AppState>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let pressed = inputs.iter().any(|(input, _)| input.buttons & INPUT_PAUSE != 0);
+    if !pressed {
+        debounce.0 = false;
+        return;
+    }
+    if debounce.0 {
+        return;
+    }
+    debounce.0 = true;
+
+    match app_state.current() {
+        AppState::Playing => {
+            app_state.push(AppState::Paused).ok();
+            rapier_config.physics_pipeline_active = false;
+        }
+        AppState::Paused => {
+            app_state.pop().ok();
+            rapier_config.physics_pipeline_active = true;
+        }
+        _ => {}
+    }
+}
+
+/// `toggle_pause`'s run criteria: unlike the rest of `gameplay_set`, it needs to keep running
+/// while `Paused` too, or nobody could ever un-pause.
+fn run_if_playing_or_paused(app_state: Res<EngineState<AppState>>) -> ShouldRun {
+    match app_state.current() {
+        AppState::Playing | AppState::Paused => ShouldRun::Yes,
+        _ => ShouldRun::No,
+    }
+}
+
+fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("SHURIKEN WORKSHOP\n\n", TextStyle { font: font.clone(), font_size: 60.0, color: Color::WHITE }),
+            TextSection::new("Press Enter to start", TextStyle { font, font_size: 30.0, color: Color::WHITE }),
+        ])
+            .with_text_alignment(TextAlignment::CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect { left: Val::Percent(50.0), top: Val::Percent(50.0), ..default() },
+                ..default()
+            }),
+        MenuUi,
+    ));
+}
+
+fn menu_input(keyboard_input: Res<Input<KeyCode>>, mut app_state: ResMut<EngineState<AppState>>) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        app_state.set(AppState::Playing).ok();
+    }
+}
+
+fn teardown_menu(mut commands: Commands, menu_query: Query<Entity, With<MenuUi>>) {
+    for entity in &menu_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn enter_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state: Res<State>,
+    shuriken_query: Query<Entity, With<Shuriken>>,
+) {
+    for entity in &shuriken_query {
+        commands.entity(entity).despawn();
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("GAME OVER\n\n", TextStyle { font: font.clone(), font_size: 60.0, color: Color::WHITE }),
+            TextSection::new(format!("You blocked {} shuriken(s)\n\n", state.blocked), TextStyle { font: font.clone(), font_size: 30.0, color: Color::WHITE }),
+            TextSection::new("Press Enter to restart", TextStyle { font, font_size: 30.0, color: Color::WHITE }),
+        ])
+            .with_text_alignment(TextAlignment::CENTER)
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect { left: Val::Percent(50.0), top: Val::Percent(50.0), ..default() },
+                ..default()
+            }),
+        GameOverUi,
+    ));
+}
+
+fn game_over_input(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut app_state: ResMut<EngineState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        commands.insert_resource(State::default());
+        spawn_health_markers(&mut commands);
+        app_state.set(AppState::Playing).ok();
+    }
+}
+
+fn teardown_game_over(mut commands: Commands, game_over_query: Query<Entity, With<GameOverUi>>) {
+    for entity in &game_over_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn setup_diagnostics_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font, font_size: 20.0, color: Color::WHITE })
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect { left: Val::Px(5.0), top: Val::Px(5.0), ..default() },
+                ..default()
+            }),
+        DiagnosticsHud,
+    ));
+}
+
+fn update_diagnostics_hud(
+    diagnostics: Res<Diagnostics>,
+    all_entities: Query<Entity>,
+    mut hud_query: Query<&mut Text, With<DiagnosticsHud>>,
+) {
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|d| d.smoothed())
+        .unwrap_or(0.0);
+    let frame_time_ms = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|d| d.smoothed())
+        .unwrap_or(0.0) * 1000.0;
+
+    for mut text in &mut hud_query {
+        text.sections[0].value = format!(
+            "{fps:.0} fps / {frame_time_ms:.2} ms\n{} entities",
+            all_entities.iter().count()
+        );
+    }
+}
+
+/// Updates the high-water mark used for the peak-entity-count line in `report_on_exit`.
+fn track_peak_entities(all_entities: Query<Entity>, mut peak: ResMut<PeakEntities>) {
+    peak.0 = peak.0.max(all_entities.iter().count());
+}
+
+/// Prints the final tally once the app is shutting down, same spot the old bare
+/// `println!("GAME OVER! ...")` used to live before the scoreboard moved onto the game-over
+/// screen; bench mode additionally reports the peak entity count it saw.
+fn report_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    state: Res<State>,
+    peak: Res<PeakEntities>,
+    bench_mode: Option<Res<BenchMode>>,
+) {
+    if exit_events.iter().next().is_none() {
+        return;
+    }
+
+    if bench_mode.is_some() {
+        println!("Exiting. Blocked {} shuriken(s), peak entity count {}", state.blocked, peak.0);
+    } else {
+        println!("Exiting. Blocked {} shuriken(s)", state.blocked);
     }
 }